@@ -0,0 +1,115 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Minimal FFI surface for `libseccomp`, plus the raw classic-BPF constants
+//! used to describe `sock_filter` programs (the format the kernel's
+//! `SECCOMP_SET_MODE_FILTER` expects, and the format `seccomp_export_bpf`
+//! emits).
+
+#![allow(non_camel_case_types)]
+
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+
+/// Opaque `libseccomp` filter context.
+pub type scmp_filter_ctx = *mut c_void;
+
+/// `errno` returned by `libseccomp` when a syscall can't be resolved.
+pub const __NR_SCMP_ERROR: c_int = -1;
+
+/// `libseccomp` returns `-EEXIST` from `seccomp_arch_add` when the arch is
+/// already present in the filter; that's not a real failure for us.
+pub const MINUS_EEXIST: c_int = -17;
+
+/// A single `libseccomp` argument comparator, mirroring `struct
+/// scmp_arg_cmp` from `<seccomp.h>`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct scmp_arg_cmp {
+    pub arg: c_uint,
+    pub op: c_uint,
+    pub datum_a: u64,
+    pub datum_b: u64,
+}
+
+pub const SCMP_CMP_NE: c_uint = 1;
+pub const SCMP_CMP_LT: c_uint = 2;
+pub const SCMP_CMP_LE: c_uint = 3;
+pub const SCMP_CMP_EQ: c_uint = 4;
+pub const SCMP_CMP_GE: c_uint = 5;
+pub const SCMP_CMP_GT: c_uint = 6;
+pub const SCMP_CMP_MASKED_EQ: c_uint = 7;
+
+pub const SCMP_ACT_KILL: u32 = 0x0000_0000;
+pub const SCMP_ACT_TRAP: u32 = 0x0003_0000;
+pub const SCMP_ACT_ERRNO_BASE: u32 = 0x0005_0000;
+pub const SCMP_ACT_LOG: u32 = 0x7ffc_0000;
+pub const SCMP_ACT_ALLOW: u32 = 0x7fff_0000;
+
+pub const SCMP_ARCH_X86_64: u32 = 0xc000_003e;
+pub const SCMP_ARCH_AARCH64: u32 = 0xc000_00b7;
+
+extern "C" {
+    pub fn seccomp_init(def_action: u32) -> scmp_filter_ctx;
+    pub fn seccomp_arch_add(ctx: scmp_filter_ctx, arch_token: u32) -> c_int;
+    pub fn seccomp_syscall_resolve_name(name: *const c_char) -> c_int;
+    pub fn seccomp_rule_add(
+        ctx: scmp_filter_ctx,
+        action: u32,
+        syscall: c_int,
+        arg_cnt: c_uint,
+        ...
+    ) -> c_int;
+    pub fn seccomp_rule_add_array(
+        ctx: scmp_filter_ctx,
+        action: u32,
+        syscall: c_int,
+        arg_cnt: c_uint,
+        arg_array: *const scmp_arg_cmp,
+    ) -> c_int;
+    pub fn seccomp_export_bpf(ctx: scmp_filter_ctx, fd: c_int) -> c_int;
+}
+
+/// A single classic-BPF instruction, matching the kernel's `struct
+/// sock_filter` (`linux/filter.h`): 8 bytes, so it round-trips through the
+/// same `Vec<u64>` wire format `seccomp_export_bpf` produces.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, zerocopy::IntoBytes, zerocopy::Immutable)]
+pub struct sock_filter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+// Instruction classes.
+pub const BPF_LD: u16 = 0x00;
+pub const BPF_JMP: u16 = 0x05;
+pub const BPF_RET: u16 = 0x06;
+pub const BPF_ALU: u16 = 0x04;
+
+// `BPF_LD` sizes/modes.
+pub const BPF_W: u16 = 0x00;
+pub const BPF_ABS: u16 = 0x20;
+
+// `BPF_JMP` ops.
+pub const BPF_JA: u16 = 0x00;
+pub const BPF_JEQ: u16 = 0x10;
+pub const BPF_JGT: u16 = 0x20;
+pub const BPF_JGE: u16 = 0x30;
+pub const BPF_K: u16 = 0x00;
+
+// `BPF_ALU` ops.
+pub const BPF_AND: u16 = 0x50;
+
+/// Offsets into `struct seccomp_data`, in bytes.
+pub const SECCOMP_DATA_NR_OFFSET: u32 = 0;
+pub const SECCOMP_DATA_ARCH_OFFSET: u32 = 4;
+pub const SECCOMP_DATA_ARGS_OFFSET: u32 = 16;
+
+/// Matches the kernel's `struct sock_fprog` (`linux/filter.h`): what
+/// `prctl(PR_SET_SECCOMP, SECCOMP_MODE_FILTER, ...)` expects.
+#[repr(C)]
+pub struct sock_fprog {
+    pub len: u16,
+    pub filter: *mut sock_filter,
+}
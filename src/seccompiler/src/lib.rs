@@ -13,8 +13,13 @@ use bincode::Error as BincodeError;
 mod bindings;
 use bindings::*;
 
+mod native;
+mod syscalls;
+
 pub mod types;
 pub use types::*;
+pub mod validate;
+pub use validate::*;
 use zerocopy::IntoBytes;
 
 /// Binary filter compilation errors.
@@ -48,6 +53,14 @@ pub enum CompilationError {
     OutputCreate(std::io::Error),
     /// Cannot serialize bfp: {0}
     BincodeSerialize(BincodeError),
+    /// Native backend cannot resolve syscall: {0}
+    NativeUnknownSyscall(String),
+    /// Native backend produced a jump that doesn't fit a BPF program
+    NativeJumpOutOfRange,
+    /// Undefined seccomp syscall group: {0}
+    UndefinedGroup(String),
+    /// Denylist filter_action is not more restrictive than allow: {0:?}
+    DenylistNotRestrictive(SeccompAction),
 }
 
 pub fn compile_bpf(
@@ -55,17 +68,46 @@ pub fn compile_bpf(
     arch: &str,
     out_path: &str,
     basic: bool,
+    backend: Backend,
+    mode: CompilationMode,
 ) -> Result<(), CompilationError> {
     let mut file_content = String::new();
     File::open(input_path)
         .map_err(CompilationError::IntputOpen)?
         .read_to_string(&mut file_content)
         .map_err(CompilationError::InputRead)?;
-    let bpf_map_json: BpfJson =
-        serde_json::from_str(&file_content).map_err(CompilationError::JsonDeserialize)?;
+    let bpf_map_json = BpfJson::parse(&file_content)?;
 
     let arch = TargetArch::from_str(arch).map_err(CompilationError::ArchParse)?;
 
+    if backend == Backend::Native {
+        let mut bpf_map: HashMap<String, Vec<Vec<u64>>> = HashMap::new();
+        for (name, filter) in bpf_map_json.0.iter() {
+            let mut programs = vec![native::pack(&native::compile_filter(
+                filter, arch, basic, mode,
+            )?)];
+
+            if let Some(denylist) = &filter.denylist {
+                let denylist_filter = SeccompFilter {
+                    default_action: SeccompAction::Allow,
+                    filter_action: denylist.filter_action,
+                    filter: denylist.filter.clone(),
+                    denylist: None,
+                };
+                programs.push(native::pack(&native::compile_filter(
+                    &denylist_filter,
+                    arch,
+                    basic,
+                    mode,
+                )?));
+            }
+
+            bpf_map.insert(name.clone(), programs);
+        }
+
+        return write_bpf_map(out_path, &bpf_map);
+    }
+
     // SAFETY: Safe because the parameters are valid.
     let memfd_fd = unsafe { libc::memfd_create(c"bpf".as_ptr().cast(), 0) };
     if memfd_fd < 0 {
@@ -77,105 +119,143 @@ pub fn compile_bpf(
     // SAFETY: Safe because the parameters are valid.
     let mut memfd = unsafe { File::from_raw_fd(memfd_fd) };
 
-    let mut bpf_map: HashMap<String, Vec<u64>> = HashMap::new();
+    let mut bpf_map: HashMap<String, Vec<Vec<u64>>> = HashMap::new();
     for (name, filter) in bpf_map_json.0.iter() {
-        let default_action = filter.default_action.to_scmp_type();
-        let filter_action = filter.filter_action.to_scmp_type();
+        let mut programs = vec![compile_one_libseccomp_program(
+            &mut memfd,
+            arch,
+            basic,
+            mode.resolve_action(filter.default_action),
+            mode.resolve_action(filter.filter_action),
+            &filter.filter,
+        )?];
+
+        if let Some(denylist) = &filter.denylist {
+            programs.push(compile_one_libseccomp_program(
+                &mut memfd,
+                arch,
+                basic,
+                mode.resolve_action(SeccompAction::Allow),
+                mode.resolve_action(denylist.filter_action),
+                &denylist.filter,
+            )?);
+        }
+
+        bpf_map.insert(name.clone(), programs);
+    }
+
+    write_bpf_map(out_path, &bpf_map)
+}
+
+/// Serializes `bpf_map` to `out_path` in the wire format the jailer/VMM read
+/// back at startup. Shared by both the native and libseccomp backends, which
+/// otherwise only differ in how each program is compiled.
+fn write_bpf_map(
+    out_path: &str,
+    bpf_map: &HashMap<String, Vec<Vec<u64>>>,
+) -> Result<(), CompilationError> {
+    let output_file = File::create(out_path).map_err(CompilationError::OutputCreate)?;
+    bincode::serialize_into(output_file, bpf_map).map_err(CompilationError::BincodeSerialize)
+}
 
+/// Compiles a single `libseccomp` BPF program (one `seccomp_init` context,
+/// exported through `memfd`) for `rules`.
+fn compile_one_libseccomp_program(
+    memfd: &mut File,
+    arch: TargetArch,
+    basic: bool,
+    default_action: u32,
+    filter_action: u32,
+    rules: &[SeccompRule],
+) -> Result<Vec<u64>, CompilationError> {
+    // SAFETY: Safe as all args are correct.
+    let bpf_filter = unsafe {
+        let r = seccomp_init(default_action);
+        if r.is_null() {
+            return Err(CompilationError::LibSeccompContext);
+        }
+        r
+    };
+
+    // SAFETY: Safe as all args are correct.
+    unsafe {
+        let r = seccomp_arch_add(bpf_filter, arch.to_scmp_type());
+        if r != 0 && r != MINUS_EEXIST {
+            return Err(CompilationError::LibSeccompAddArch);
+        }
+    }
+
+    for rule in rules {
         // SAFETY: Safe as all args are correct.
-        let bpf_filter = {
-            let r = seccomp_init(default_action);
-            if r.is_null() {
-                return Err(CompilationError::LibSeccompContext);
+        let syscall = unsafe {
+            let r = seccomp_syscall_resolve_name(rule.syscall.as_ptr());
+            if r == __NR_SCMP_ERROR {
+                return Err(CompilationError::LibSeccompResolveSyscall);
             }
             r
         };
 
-        // SAFETY: Safe as all args are correct.
-        unsafe {
-            let r = seccomp_arch_add(bpf_filter, arch.to_scmp_type());
-            if r != 0 && r != MINUS_EEXIST {
-                return Err(CompilationError::LibSeccompAddArch);
+        // TODO remove when we drop deprecated "basic" arg from cli.
+        // "basic" bpf means it ignores condition checks.
+        if basic {
+            // SAFETY: Safe as all args are correct.
+            unsafe {
+                if seccomp_rule_add(bpf_filter, filter_action, syscall, 0) != 0 {
+                    return Err(CompilationError::LibSeccompAddRule);
+                }
             }
-        }
+        } else if let Some(conditions) = &rule.args {
+            let comparators = conditions
+                .iter()
+                .map(SeccompCondition::to_scmp_type)
+                .collect::<Vec<scmp_arg_cmp>>();
 
-        for rule in filter.filter.iter() {
             // SAFETY: Safe as all args are correct.
-            let syscall = unsafe {
-                let r = seccomp_syscall_resolve_name(rule.syscall.as_ptr());
-                if r == __NR_SCMP_ERROR {
-                    return Err(CompilationError::LibSeccompResolveSyscall);
-                }
-                r
-            };
-
-            // TODO remove when we drop deprecated "basic" arg from cli.
-            // "basic" bpf means it ignores condition checks.
-            if basic {
-                // SAFETY: Safe as all args are correct.
-                unsafe {
-                    if seccomp_rule_add(bpf_filter, filter_action, syscall, 0) != 0 {
-                        return Err(CompilationError::LibSeccompAddRule);
-                    }
-                }
-            } else if let Some(rules) = &rule.args {
-                let comparators = rules
-                    .iter()
-                    .map(|rule| rule.to_scmp_type())
-                    .collect::<Vec<scmp_arg_cmp>>();
-
-                // SAFETY: Safe as all args are correct.
-                // We can assume no one will define u32::MAX
-                // filters for a syscall.
-                #[allow(clippy::cast_possible_truncation)]
-                unsafe {
-                    if seccomp_rule_add_array(
-                        bpf_filter,
-                        filter_action,
-                        syscall,
-                        comparators.len() as u32,
-                        comparators.as_ptr(),
-                    ) != 0
-                    {
-                        return Err(CompilationError::LibSeccompAddRule);
-                    }
+            // We can assume no one will define u32::MAX
+            // filters for a syscall.
+            #[allow(clippy::cast_possible_truncation)]
+            unsafe {
+                if seccomp_rule_add_array(
+                    bpf_filter,
+                    filter_action,
+                    syscall,
+                    comparators.len() as u32,
+                    comparators.as_ptr(),
+                ) != 0
+                {
+                    return Err(CompilationError::LibSeccompAddRule);
                 }
-            } else {
-                // SAFETY: Safe as all args are correct.
-                unsafe {
-                    if seccomp_rule_add(bpf_filter, filter_action, syscall, 0) != 0 {
-                        return Err(CompilationError::LibSeccompAddRule);
-                    }
+            }
+        } else {
+            // SAFETY: Safe as all args are correct.
+            unsafe {
+                if seccomp_rule_add(bpf_filter, filter_action, syscall, 0) != 0 {
+                    return Err(CompilationError::LibSeccompAddRule);
                 }
             }
         }
+    }
 
-        // SAFETY: Safe as all args are correect.
-        unsafe {
-            if seccomp_export_bpf(bpf_filter, memfd.as_raw_fd()) != 0 {
-                return Err(CompilationError::LibSeccompExport);
-            }
+    // SAFETY: Safe as all args are correect.
+    unsafe {
+        if seccomp_export_bpf(bpf_filter, memfd.as_raw_fd()) != 0 {
+            return Err(CompilationError::LibSeccompExport);
         }
-        memfd.rewind().map_err(CompilationError::MemfdRewind)?;
-
-        // Cast is safe because usize == u64
-        #[allow(clippy::cast_possible_truncation)]
-        let size = memfd.metadata().unwrap().size() as usize;
-        // Bpf instructions are 8 byte values and 4 byte alignment.
-        // We use u64 to satisfy these requirements.
-        let instructions = size / std::mem::size_of::<u64>();
-        let mut bpf = vec![0_u64; instructions];
-
-        memfd
-            .read_exact(bpf.as_mut_bytes())
-            .map_err(CompilationError::MemfdRead)?;
-        memfd.rewind().map_err(CompilationError::MemfdRewind)?;
-
-        bpf_map.insert(name.clone(), bpf);
     }
+    memfd.rewind().map_err(CompilationError::MemfdRewind)?;
 
-    let output_file = File::create(out_path).map_err(CompilationError::OutputCreate)?;
+    // Cast is safe because usize == u64
+    #[allow(clippy::cast_possible_truncation)]
+    let size = memfd.metadata().unwrap().size() as usize;
+    // Bpf instructions are 8 byte values and 4 byte alignment.
+    // We use u64 to satisfy these requirements.
+    let instructions = size / std::mem::size_of::<u64>();
+    let mut bpf = vec![0_u64; instructions];
+
+    memfd
+        .read_exact(bpf.as_mut_bytes())
+        .map_err(CompilationError::MemfdRead)?;
+    memfd.rewind().map_err(CompilationError::MemfdRewind)?;
 
-    bincode::serialize_into(output_file, &bpf_map).map_err(CompilationError::BincodeSerialize)?;
-    Ok(())
+    Ok(bpf)
 }
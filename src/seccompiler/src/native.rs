@@ -0,0 +1,597 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pure-Rust classic-BPF code generator: compiles a [`SeccompFilter`] down
+//! to a `sock_filter` program directly, without going through
+//! `libseccomp`/`memfd`. This is what lets us compile a filter for an arch
+//! that differs from the build host's.
+
+use std::collections::HashMap;
+
+use crate::bindings::*;
+use crate::syscalls;
+use crate::types::{CompilationMode, SeccompCmpOp, SeccompCondition, SeccompFilter, TargetArch};
+use crate::CompilationError;
+
+fn stmt(code: u16, k: u32) -> sock_filter {
+    sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter { code, jt, jf, k }
+}
+
+fn ret(k: u32) -> sock_filter {
+    sock_filter {
+        code: BPF_RET | BPF_K,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+/// Relative jump distance for a forward jump from `from` to `to`, as a BPF
+/// `jt`/`jf` byte (the gap between the two, since `jt`/`jf` count the
+/// number of instructions to skip past the next one). Only safe to use for
+/// jumps that are known to stay local (e.g. within a single condition's own
+/// instructions) -- anything that might have to skip over other rules'
+/// blocks should go through a [`push_cond`] stub instead.
+fn rel_jump(from: usize, to: usize) -> Result<u8, CompilationError> {
+    to.checked_sub(from + 1)
+        .and_then(|dist| u8::try_from(dist).ok())
+        .ok_or(CompilationError::NativeJumpOutOfRange)
+}
+
+/// Like [`rel_jump`], but for a `BPF_JA` stub's `k` field, which is a full
+/// `u32` rather than the single byte `jt`/`jf` use. `BPF_JA` is how a
+/// [`push_cond`] stub reaches a target that's too far away for a direct
+/// conditional jump.
+fn rel_jump_far(from: usize, to: usize) -> Result<u32, CompilationError> {
+    to.checked_sub(from + 1)
+        .and_then(|dist| u32::try_from(dist).ok())
+        .ok_or(CompilationError::NativeJumpOutOfRange)
+}
+
+/// Appends a conditional jump whose failure path is always a short, fixed
+/// hop into an adjacent `BPF_JA` stub, rather than a direct `jf`. A filter
+/// with many syscalls/argument variants can easily need a failing
+/// condition to skip forward past more than 255 instructions to reach the
+/// default action (or the next variant of the same syscall) -- more than a
+/// single-byte `jt`/`jf` can encode. The stub sits right next to the
+/// comparison, so the jump into it never overflows; the stub's `k` (a full
+/// `u32`, via `BPF_JA`) carries the real, possibly-distant target, recorded
+/// in `far_jumps` for the caller to patch once the whole program's layout
+/// is known.
+fn push_cond(prog: &mut Vec<sock_filter>, code: u16, k: u32, far_jumps: &mut Vec<usize>) {
+    prog.push(jump(code, k, 1, 0));
+    far_jumps.push(prog.len());
+    prog.push(jump(BPF_JMP | BPF_JA, 0, 0, 0));
+}
+
+/// [`push_cond`] with the comparison's sense inverted: a *true* result is
+/// what sends execution to the far stub, while a false result falls
+/// through. Used to express a comparator BPF has no direct opcode for (e.g.
+/// "not equal", "less than") as the negation of one it does.
+fn push_cond_on_true(prog: &mut Vec<sock_filter>, code: u16, k: u32, far_jumps: &mut Vec<usize>) {
+    prog.push(jump(code, k, 0, 1));
+    far_jumps.push(prog.len());
+    prog.push(jump(BPF_JMP | BPF_JA, 0, 0, 0));
+}
+
+/// Appends the instructions for a single argument condition. On success,
+/// execution falls through to the next instruction; on failure, it jumps
+/// (via a [`push_cond`] stub) to a target the caller patches in once the
+/// whole program's length is known, recording each stub's index in
+/// `far_jumps`.
+fn append_condition(
+    prog: &mut Vec<sock_filter>,
+    cond: &SeccompCondition,
+    far_jumps: &mut Vec<usize>,
+) -> Result<(), CompilationError> {
+    let arg_offset = SECCOMP_DATA_ARGS_OFFSET + 8 * u32::from(cond.arg_index);
+    let hi_offset = arg_offset + 4;
+    let lo_offset = arg_offset;
+    let val_hi = (cond.val >> 32) as u32;
+    let val_lo = cond.val as u32;
+
+    match cond.op {
+        SeccompCmpOp::Eq => {
+            prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, hi_offset));
+            push_cond(prog, BPF_JMP | BPF_JEQ | BPF_K, val_hi, far_jumps);
+            prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, lo_offset));
+            push_cond(prog, BPF_JMP | BPF_JEQ | BPF_K, val_lo, far_jumps);
+        }
+        SeccompCmpOp::MaskedEq => {
+            let mask_hi = (cond.mask >> 32) as u32;
+            let mask_lo = cond.mask as u32;
+            prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, hi_offset));
+            prog.push(stmt(BPF_ALU | BPF_AND | BPF_K, mask_hi));
+            push_cond(prog, BPF_JMP | BPF_JEQ | BPF_K, val_hi & mask_hi, far_jumps);
+            prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, lo_offset));
+            prog.push(stmt(BPF_ALU | BPF_AND | BPF_K, mask_lo));
+            push_cond(prog, BPF_JMP | BPF_JEQ | BPF_K, val_lo & mask_lo, far_jumps);
+        }
+        SeccompCmpOp::Gt | SeccompCmpOp::Ge => {
+            // hi > val_hi: the 64-bit value is greater regardless of the
+            // low word, so short-circuit straight to success. This jump
+            // only has to skip the rest of *this condition's* own
+            // instructions, so it stays local and doesn't need a stub.
+            prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, hi_offset));
+            let hi_gt_idx = prog.len();
+            prog.push(jump(BPF_JMP | BPF_JGT | BPF_K, val_hi, 0, 0));
+            // hi == val_hi: the low word decides it; otherwise (hi <
+            // val_hi) it's a fail.
+            push_cond(prog, BPF_JMP | BPF_JEQ | BPF_K, val_hi, far_jumps);
+            prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, lo_offset));
+            let lo_op = if matches!(cond.op, SeccompCmpOp::Gt) {
+                BPF_JGT
+            } else {
+                BPF_JGE
+            };
+            push_cond(prog, BPF_JMP | lo_op | BPF_K, val_lo, far_jumps);
+            let success_idx = prog.len();
+            prog[hi_gt_idx].jt = rel_jump(hi_gt_idx, success_idx)?;
+        }
+        SeccompCmpOp::Ne => {
+            // hi != val_hi already settles it; otherwise (hi == val_hi) the
+            // low word decides, and a match there is the only way to fail.
+            prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, hi_offset));
+            let hi_eq_idx = prog.len();
+            prog.push(jump(BPF_JMP | BPF_JEQ | BPF_K, val_hi, 0, 0));
+            prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, lo_offset));
+            push_cond_on_true(prog, BPF_JMP | BPF_JEQ | BPF_K, val_lo, far_jumps);
+            let success_idx = prog.len();
+            prog[hi_eq_idx].jf = rel_jump(hi_eq_idx, success_idx)?;
+        }
+        SeccompCmpOp::Lt | SeccompCmpOp::Le => {
+            // x < v / x <= v have no direct BPF opcode, so each is computed
+            // as the negation of the `Gt`/`Ge` it's paired with: hi > val_hi
+            // rules it out outright, hi == val_hi defers to the low word,
+            // and hi < val_hi (the only case left once the first check
+            // fails) settles it immediately.
+            prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, hi_offset));
+            push_cond_on_true(prog, BPF_JMP | BPF_JGT | BPF_K, val_hi, far_jumps);
+            let hi_eq_idx = prog.len();
+            prog.push(jump(BPF_JMP | BPF_JEQ | BPF_K, val_hi, 0, 0));
+            prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, lo_offset));
+            let lo_op = if matches!(cond.op, SeccompCmpOp::Lt) {
+                BPF_JGE
+            } else {
+                BPF_JGT
+            };
+            push_cond_on_true(prog, BPF_JMP | lo_op | BPF_K, val_lo, far_jumps);
+            let success_idx = prog.len();
+            prog[hi_eq_idx].jf = rel_jump(hi_eq_idx, success_idx)?;
+        }
+    }
+    Ok(())
+}
+
+/// Compiles one thread's [`SeccompFilter`] into a classic-BPF program for
+/// `arch`, without calling into `libseccomp`.
+///
+/// `basic` mirrors the `libseccomp` backend's deprecated "basic" mode: rule
+/// argument conditions are ignored and every listed syscall is allowed (or
+/// denied) unconditionally. `mode` controls whether the filter's actual
+/// actions are installed or overridden for log-only auditing.
+pub(crate) fn compile_filter(
+    filter: &SeccompFilter,
+    arch: TargetArch,
+    basic: bool,
+    mode: CompilationMode,
+) -> Result<Vec<sock_filter>, CompilationError> {
+    let default_action = mode.resolve_action(filter.default_action);
+    let filter_action = mode.resolve_action(filter.filter_action);
+
+    let mut prog = Vec::new();
+
+    prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, SECCOMP_DATA_ARCH_OFFSET));
+    // A real Firecracker thread profile can easily have dozens of
+    // argument-conditioned variants plus 40+ other syscalls, which pushes
+    // the distance from an early check to the default action (or the
+    // program's only dispatch table) well past what a single-byte `jt`/`jf`
+    // can encode. Every forward jump below that isn't known to stay local
+    // goes through a `push_cond` stub instead, so no single `jt`/`jf` ever
+    // has to span more than a couple of instructions -- the potentially
+    // long-distance part of the jump lives in the stub's `BPF_JA` `k`
+    // field, which is a full `u32`.
+    let mut arch_far_jumps = Vec::with_capacity(1);
+    push_cond(
+        &mut prog,
+        BPF_JMP | BPF_JEQ | BPF_K,
+        arch.to_scmp_type(),
+        &mut arch_far_jumps,
+    );
+    let arch_fail_stub_idx = arch_far_jumps[0];
+
+    prog.push(stmt(BPF_LD | BPF_W | BPF_ABS, SECCOMP_DATA_NR_OFFSET));
+
+    // Group rules by syscall number, so that multiple argument-conditioned
+    // variants for the same syscall (as the `groups` merge in types.rs can
+    // produce) share a single dispatch check and land in consecutive
+    // blocks. A failing condition then falls through to the *next variant
+    // of the same syscall*, not straight to the default action -- matching
+    // "OR of all variants", not "only the first dispatched variant".
+    let mut groups: Vec<(u32, Vec<usize>)> = Vec::new();
+    let mut group_of_nr: HashMap<u32, usize> = HashMap::new();
+    for (i, rule) in filter.filter.iter().enumerate() {
+        let name = rule.syscall.to_str().map_err(|_| {
+            CompilationError::NativeUnknownSyscall(rule.syscall.to_string_lossy().into_owned())
+        })?;
+        let nr = syscalls::resolve(arch, name)
+            .ok_or_else(|| CompilationError::NativeUnknownSyscall(name.to_string()))?;
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        let nr = nr as u32;
+        let gi = *group_of_nr.entry(nr).or_insert_with(|| {
+            groups.push((nr, Vec::new()));
+            groups.len() - 1
+        });
+        groups[gi].1.push(i);
+    }
+
+    // Each dispatch check always gets its own "jump to my block" stub (its
+    // `jt`), since that block's position depends on every other group laid
+    // out before it and can be arbitrarily far away. A mismatch (`jf`)
+    // falls straight through to the next dispatch check -- the checks are
+    // laid out back-to-back -- except for the last one, which needs its
+    // own stub to reach the default action.
+    let mut dispatch_jt_stubs = Vec::with_capacity(groups.len());
+    let mut dispatch_jf_stub = None;
+    for (gi, &(nr, _)) in groups.iter().enumerate() {
+        prog.push(jump(BPF_JMP | BPF_JEQ | BPF_K, nr, 0, 1));
+        dispatch_jt_stubs.push(prog.len());
+        prog.push(jump(BPF_JMP | BPF_JA, 0, 0, 0));
+        if gi + 1 == groups.len() {
+            dispatch_jf_stub = Some(prog.len());
+            prog.push(jump(BPF_JMP | BPF_JA, 0, 0, 0));
+        }
+    }
+
+    // Lay out each group's variant blocks consecutively (in the same order
+    // as `groups`), so "fall through to the next variant" is just "fall
+    // through to the next block" for every variant but the last in a group.
+    let mut block_starts = vec![0usize; filter.filter.len()];
+    let mut rule_far_jumps: Vec<Vec<usize>> = vec![Vec::new(); filter.filter.len()];
+    for (_, members) in &groups {
+        for &rule_idx in members {
+            block_starts[rule_idx] = prog.len();
+            let rule = &filter.filter[rule_idx];
+            let mut local_far_jumps = Vec::new();
+            match (&rule.args, basic) {
+                (Some(conditions), false) => {
+                    for cond in conditions {
+                        append_condition(&mut prog, cond, &mut local_far_jumps)?;
+                    }
+                    prog.push(ret(filter_action));
+                }
+                _ => prog.push(ret(filter_action)),
+            }
+            rule_far_jumps[rule_idx] = local_far_jumps;
+        }
+    }
+
+    let default_ret_idx = prog.len();
+    prog.push(ret(default_action));
+
+    prog[arch_fail_stub_idx].k = rel_jump_far(arch_fail_stub_idx, default_ret_idx)?;
+
+    for (gi, (_, members)) in groups.iter().enumerate() {
+        let jt_stub = dispatch_jt_stubs[gi];
+        prog[jt_stub].k = rel_jump_far(jt_stub, block_starts[members[0]])?;
+    }
+    if let Some(jf_stub) = dispatch_jf_stub {
+        prog[jf_stub].k = rel_jump_far(jf_stub, default_ret_idx)?;
+    }
+
+    for (_, members) in &groups {
+        for (k, &rule_idx) in members.iter().enumerate() {
+            // A variant's failing condition falls through to the next
+            // variant for the same syscall, or to the default action if
+            // it's the last one in the group.
+            let target = if k + 1 < members.len() {
+                block_starts[members[k + 1]]
+            } else {
+                default_ret_idx
+            };
+            for &stub_idx in &rule_far_jumps[rule_idx] {
+                prog[stub_idx].k = rel_jump_far(stub_idx, target)?;
+            }
+        }
+    }
+
+    Ok(prog)
+}
+
+/// Packs a `sock_filter` program into the same `Vec<u64>` wire format the
+/// `libseccomp` backend produces via `seccomp_export_bpf` (each instruction
+/// is 8 bytes: `code:u16, jt:u8, jf:u8, k:u32`, native-endian).
+pub(crate) fn pack(prog: &[sock_filter]) -> Vec<u64> {
+    prog.iter()
+        .map(|f| {
+            u64::from(f.code)
+                | (u64::from(f.jt) << 16)
+                | (u64::from(f.jf) << 24)
+                | (u64::from(f.k) << 32)
+        })
+        .collect()
+}
+
+/// The inverse of [`pack`]: turns the compiled wire format back into a
+/// `sock_filter` program the kernel (or `validate_bpf`) can install.
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn unpack(words: &[u64]) -> Vec<sock_filter> {
+    words
+        .iter()
+        .map(|&w| sock_filter {
+            code: w as u16,
+            jt: (w >> 16) as u8,
+            jf: (w >> 24) as u8,
+            k: (w >> 32) as u32,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+    use crate::types::{SeccompAction, SeccompRule};
+
+    /// Minimal classic-BPF interpreter covering just the opcodes
+    /// `compile_filter` ever emits, so tests can check the compiled program
+    /// actually behaves as intended rather than just that it compiles.
+    fn run(prog: &[sock_filter], data: &[u8]) -> u32 {
+        let mut pc = 0usize;
+        let mut acc = 0u32;
+        loop {
+            let ins = prog[pc];
+            let load =
+                |k: u32| u32::from_ne_bytes(data[k as usize..k as usize + 4].try_into().unwrap());
+            match ins.code {
+                c if c == BPF_LD | BPF_W | BPF_ABS => {
+                    acc = load(ins.k);
+                    pc += 1;
+                }
+                c if c == BPF_ALU | BPF_AND | BPF_K => {
+                    acc &= ins.k;
+                    pc += 1;
+                }
+                c if c == BPF_JMP | BPF_JA => pc += 1 + ins.k as usize,
+                c if c == BPF_JMP | BPF_JEQ | BPF_K => {
+                    pc += 1 + usize::from(if acc == ins.k { ins.jt } else { ins.jf });
+                }
+                c if c == BPF_JMP | BPF_JGT | BPF_K => {
+                    pc += 1 + usize::from(if acc > ins.k { ins.jt } else { ins.jf });
+                }
+                c if c == BPF_JMP | BPF_JGE | BPF_K => {
+                    pc += 1 + usize::from(if acc >= ins.k { ins.jt } else { ins.jf });
+                }
+                c if c == BPF_RET | BPF_K => return ins.k,
+                other => panic!("interpreter hit an unexpected opcode {other:#x}"),
+            }
+        }
+    }
+
+    const DATA_LEN: usize = 16 + 6 * 8;
+
+    fn seccomp_data(arch: TargetArch, nr: i64, args: [u64; 6]) -> [u8; DATA_LEN] {
+        let mut buf = [0u8; DATA_LEN];
+        #[allow(clippy::cast_possible_truncation)]
+        buf[0..4].copy_from_slice(&(nr as u32).to_ne_bytes());
+        buf[4..8].copy_from_slice(&arch.to_scmp_type().to_ne_bytes());
+        for (i, arg) in args.iter().enumerate() {
+            let off = 16 + 8 * i;
+            buf[off..off + 4].copy_from_slice(&(*arg as u32).to_ne_bytes());
+            buf[off + 4..off + 8].copy_from_slice(&((*arg >> 32) as u32).to_ne_bytes());
+        }
+        buf
+    }
+
+    fn rule(syscall: &str, args: Option<Vec<SeccompCondition>>) -> SeccompRule {
+        SeccompRule {
+            syscall: CString::new(syscall).unwrap(),
+            args,
+        }
+    }
+
+    /// A filter listing every known x86_64 syscall unconditionally compiles
+    /// to well over 255 instructions, so the arch check's failure path has
+    /// to reach the default action through a far (`u32`) jump rather than
+    /// a single `jt`/`jf` byte; every dispatch check also reaches its block
+    /// through a `BPF_JA` stub. Decode the program and confirm both a
+    /// late-dispatched and an unmatched syscall land on the right `ret`.
+    #[test]
+    fn far_dispatch_jumps_land_on_the_right_block() {
+        let names: Vec<&str> = syscalls::names(TargetArch::X86_64).collect();
+        assert!(
+            names.len() > 90,
+            "need enough syscalls to exceed 255 instructions"
+        );
+
+        let filter = SeccompFilter {
+            default_action: SeccompAction::Kill,
+            filter_action: SeccompAction::Allow,
+            filter: names.iter().map(|&name| rule(name, None)).collect(),
+            denylist: None,
+        };
+
+        let prog =
+            compile_filter(&filter, TargetArch::X86_64, false, CompilationMode::Enforce).unwrap();
+        assert!(
+            prog.len() > 255,
+            "fixture should exceed a single-byte jump range"
+        );
+
+        let last = *names.last().unwrap();
+        let nr = syscalls::resolve(TargetArch::X86_64, last).unwrap();
+        let data = seccomp_data(TargetArch::X86_64, nr, [0; 6]);
+        assert_eq!(run(&prog, &data), SeccompAction::Allow.to_scmp_type());
+
+        let data = seccomp_data(TargetArch::X86_64, 999_999, [0; 6]);
+        assert_eq!(run(&prog, &data), SeccompAction::Kill.to_scmp_type());
+    }
+
+    /// Compiles a filter with a single condition on `arg0` and runs it
+    /// against `arg`, returning the resulting action.
+    fn run_condition(op: SeccompCmpOp, val: u64, mask: u64, arg: u64) -> u32 {
+        let filter = SeccompFilter {
+            default_action: SeccompAction::Kill,
+            filter_action: SeccompAction::Allow,
+            filter: vec![rule(
+                "close",
+                Some(vec![SeccompCondition {
+                    arg_index: 0,
+                    op,
+                    val,
+                    mask,
+                }]),
+            )],
+            denylist: None,
+        };
+        let prog =
+            compile_filter(&filter, TargetArch::X86_64, false, CompilationMode::Enforce).unwrap();
+        let nr = syscalls::resolve(TargetArch::X86_64, "close").unwrap();
+        let mut args = [0u64; 6];
+        args[0] = arg;
+        run(&prog, &seccomp_data(TargetArch::X86_64, nr, args))
+    }
+
+    #[test]
+    fn comparators_agree_with_their_obvious_arithmetic() {
+        // A threshold that spans the hi/lo word split exercises both halves
+        // of each comparator's generated code, not just the low 32 bits.
+        let threshold = 5_000_000_000u64;
+        let allow = SeccompAction::Allow.to_scmp_type();
+        let kill = SeccompAction::Kill.to_scmp_type();
+
+        let cases: &[(SeccompCmpOp, u64, u32)] = &[
+            (SeccompCmpOp::Eq, threshold, allow),
+            (SeccompCmpOp::Eq, threshold + 1, kill),
+            (SeccompCmpOp::Ne, threshold, kill),
+            (SeccompCmpOp::Ne, threshold + 1, allow),
+            (SeccompCmpOp::Gt, threshold + 1, allow),
+            (SeccompCmpOp::Gt, threshold, kill),
+            (SeccompCmpOp::Ge, threshold, allow),
+            (SeccompCmpOp::Ge, threshold - 1, kill),
+            (SeccompCmpOp::Lt, threshold - 1, allow),
+            (SeccompCmpOp::Lt, threshold, kill),
+            (SeccompCmpOp::Le, threshold, allow),
+            (SeccompCmpOp::Le, threshold + 1, kill),
+            // Values whose high word differs from the threshold's, to
+            // exercise each comparator's hi-word short-circuit path too.
+            (SeccompCmpOp::Gt, threshold * 2, allow),
+            (SeccompCmpOp::Ge, threshold * 2, allow),
+            (SeccompCmpOp::Lt, 1, allow),
+            (SeccompCmpOp::Le, 1, allow),
+            (SeccompCmpOp::Ne, 1, allow),
+            (SeccompCmpOp::Eq, 1, kill),
+        ];
+
+        for &(op, arg, want) in cases {
+            assert_eq!(
+                run_condition(op, threshold, 0, arg),
+                want,
+                "op={op:?} arg={arg}"
+            );
+        }
+    }
+
+    #[test]
+    fn masked_eq_applies_mask_before_comparing() {
+        let allow = SeccompAction::Allow.to_scmp_type();
+        let kill = SeccompAction::Kill.to_scmp_type();
+        assert_eq!(run_condition(SeccompCmpOp::MaskedEq, 0x2, 0x3, 0x2), allow);
+        assert_eq!(run_condition(SeccompCmpOp::MaskedEq, 0x2, 0x3, 0x6), allow);
+        assert_eq!(run_condition(SeccompCmpOp::MaskedEq, 0x2, 0x3, 0x5), kill);
+    }
+
+    #[test]
+    fn log_only_mode_overrides_both_actions_but_keeps_the_rule_structure() {
+        let filter = SeccompFilter {
+            default_action: SeccompAction::Kill,
+            filter_action: SeccompAction::Errno(42),
+            filter: vec![rule("close", None)],
+            denylist: None,
+        };
+
+        let prog =
+            compile_filter(&filter, TargetArch::X86_64, false, CompilationMode::LogOnly).unwrap();
+        let log = SeccompAction::Log.to_scmp_type();
+
+        let nr = syscalls::resolve(TargetArch::X86_64, "close").unwrap();
+        let matched = seccomp_data(TargetArch::X86_64, nr, [0; 6]);
+        assert_eq!(run(&prog, &matched), log);
+
+        // A syscall the filter doesn't list still falls through to the
+        // (also log-only) default action, not the declared `kill`.
+        let unmatched = seccomp_data(TargetArch::X86_64, 999_999, [0; 6]);
+        assert_eq!(run(&prog, &unmatched), log);
+    }
+
+    /// Mirrors how `compile_bpf` layers a thread's allow-list and denylist:
+    /// two independent programs, with the kernel applying whichever
+    /// returns the more restrictive action. Confirms the denylist actually
+    /// narrows the broad "allow ioctl" without having to touch the
+    /// allow-list itself.
+    #[test]
+    fn denylist_narrows_the_allow_list_for_matching_arguments() {
+        let allow = SeccompAction::Allow.to_scmp_type();
+        let kill = SeccompAction::Kill.to_scmp_type();
+
+        let allow_list = SeccompFilter {
+            default_action: SeccompAction::Kill,
+            filter_action: SeccompAction::Allow,
+            filter: vec![rule("ioctl", None)],
+            denylist: None,
+        };
+        let denylist = SeccompFilter {
+            default_action: SeccompAction::Allow,
+            filter_action: SeccompAction::Kill,
+            filter: vec![rule(
+                "ioctl",
+                Some(vec![SeccompCondition {
+                    arg_index: 1,
+                    op: SeccompCmpOp::Eq,
+                    val: 0xDEAD,
+                    mask: 0,
+                }]),
+            )],
+            denylist: None,
+        };
+
+        let allow_prog = compile_filter(
+            &allow_list,
+            TargetArch::X86_64,
+            false,
+            CompilationMode::Enforce,
+        )
+        .unwrap();
+        let deny_prog = compile_filter(
+            &denylist,
+            TargetArch::X86_64,
+            false,
+            CompilationMode::Enforce,
+        )
+        .unwrap();
+
+        let nr = syscalls::resolve(TargetArch::X86_64, "ioctl").unwrap();
+        let narrowed = seccomp_data(TargetArch::X86_64, nr, [0, 0xDEAD, 0, 0, 0, 0]);
+        // The allow-list alone would let this through...
+        assert_eq!(run(&allow_prog, &narrowed), allow);
+        // ...but the denylist returns the more restrictive action for it.
+        assert_eq!(
+            std::cmp::min(run(&allow_prog, &narrowed), run(&deny_prog, &narrowed)),
+            kill
+        );
+
+        let untouched = seccomp_data(TargetArch::X86_64, nr, [0, 0x1234, 0, 0, 0, 0]);
+        assert_eq!(run(&allow_prog, &untouched), allow);
+        assert_eq!(run(&deny_prog, &untouched), allow);
+    }
+}
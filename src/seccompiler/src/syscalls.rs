@@ -0,0 +1,144 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Syscall name -> number tables for the native BPF backend.
+//!
+//! `libseccomp` resolves these against the *host's* syscall tables via
+//! `seccomp_syscall_resolve_name`, which is exactly what stops us from
+//! compiling a filter for an arch other than the one we're running on. The
+//! native backend instead ships its own per-arch table, covering the
+//! syscalls that appear in Firecracker's jailer/VMM/API thread profiles.
+//! Extend as needed when a profile references a syscall not listed here.
+
+use crate::types::TargetArch;
+
+macro_rules! table {
+    ($($name:literal => $nr:expr),* $(,)?) => {
+        &[$(($name, $nr)),*]
+    };
+}
+
+#[rustfmt::skip]
+const X86_64: &[(&str, i64)] = table! {
+    "read" => 0, "write" => 1, "open" => 2, "close" => 3, "stat" => 4,
+    "fstat" => 5, "lstat" => 6, "poll" => 7, "lseek" => 8, "mmap" => 9,
+    "mprotect" => 10, "munmap" => 11, "brk" => 12, "rt_sigaction" => 13,
+    "rt_sigprocmask" => 14, "rt_sigreturn" => 15, "ioctl" => 16,
+    "pread64" => 17, "pwrite64" => 18, "readv" => 19, "writev" => 20,
+    "access" => 21, "pipe" => 22, "select" => 23, "mremap" => 25,
+    "madvise" => 28, "dup" => 32, "dup2" => 33, "pause" => 34,
+    "nanosleep" => 35, "getpid" => 39, "socket" => 41, "connect" => 42,
+    "accept" => 43, "sendto" => 44, "recvfrom" => 45, "sendmsg" => 46,
+    "recvmsg" => 47, "shutdown" => 48, "bind" => 49, "listen" => 50,
+    "clone" => 56, "fork" => 57, "vfork" => 58, "execve" => 59,
+    "exit" => 60, "wait4" => 61, "kill" => 62, "fcntl" => 72,
+    "flock" => 73, "fsync" => 74, "getcwd" => 79, "getdents" => 78,
+    "rename" => 82, "mkdir" => 83, "rmdir" => 84, "creat" => 85,
+    "link" => 86, "unlink" => 87, "symlink" => 88, "readlink" => 89,
+    "chmod" => 90, "chown" => 92, "getuid" => 102, "getgid" => 104,
+    "geteuid" => 107, "getegid" => 108, "setpgid" => 109,
+    "getppid" => 110, "setsid" => 112, "sigaltstack" => 131,
+    "statfs" => 137, "fstatfs" => 138, "prctl" => 157,
+    "arch_prctl" => 158, "gettid" => 186, "time" => 201,
+    "futex" => 202, "sched_setaffinity" => 203,
+    "sched_getaffinity" => 204, "getdents64" => 217,
+    "set_tid_address" => 218, "restart_syscall" => 219,
+    "clock_gettime" => 228, "exit_group" => 231, "epoll_wait" => 232,
+    "epoll_ctl" => 233, "tgkill" => 234, "openat" => 257,
+    "mkdirat" => 258, "newfstatat" => 262, "unlinkat" => 263,
+    "readlinkat" => 267, "pselect6" => 270, "ppoll" => 271,
+    "set_robust_list" => 273, "get_robust_list" => 274,
+    "splice" => 275, "tee" => 276, "utimensat" => 280,
+    "epoll_pwait" => 281, "signalfd" => 282, "timerfd_create" => 283,
+    "eventfd" => 284, "fallocate" => 285, "timerfd_settime" => 286,
+    "timerfd_gettime" => 287, "accept4" => 288, "signalfd4" => 289,
+    "eventfd2" => 290, "epoll_create1" => 291, "dup3" => 292,
+    "pipe2" => 293, "preadv" => 295, "pwritev" => 296,
+    "recvmmsg" => 299, "prlimit64" => 302, "getrandom" => 318,
+    "memfd_create" => 319, "execveat" => 322, "statx" => 332,
+};
+
+#[rustfmt::skip]
+const AARCH64: &[(&str, i64)] = table! {
+    "io_setup" => 0, "getcwd" => 17, "dup" => 23, "dup3" => 24,
+    "fcntl" => 25, "ioctl" => 29, "mkdirat" => 34, "unlinkat" => 35,
+    "symlinkat" => 36, "linkat" => 37, "renameat" => 38,
+    "statfs" => 43, "fstatfs" => 44, "fallocate" => 47,
+    "faccessat" => 48, "chdir" => 49, "fchmodat" => 53,
+    "fchownat" => 54, "openat" => 56, "close" => 57,
+    "pipe2" => 59, "getdents64" => 61, "lseek" => 62, "read" => 63,
+    "write" => 64, "readv" => 65, "writev" => 66, "pread64" => 67,
+    "pwrite64" => 68, "pselect6" => 72, "ppoll" => 73,
+    "signalfd4" => 74, "fstat" => 80, "sync" => 81,
+    "mount" => 40, "exit" => 93, "exit_group" => 94, "futex" => 98,
+    "set_robust_list" => 99, "get_robust_list" => 100,
+    "nanosleep" => 101, "set_tid_address" => 96, "clock_gettime" => 113,
+    "clock_nanosleep" => 115, "sched_getaffinity" => 123,
+    "sched_setaffinity" => 122, "restart_syscall" => 128,
+    "kill" => 129, "tkill" => 130, "tgkill" => 131,
+    "sigaltstack" => 132, "rt_sigsuspend" => 133,
+    "rt_sigaction" => 134, "rt_sigprocmask" => 135,
+    "rt_sigpending" => 136, "rt_sigtimedwait" => 137,
+    "rt_sigqueueinfo" => 138, "rt_sigreturn" => 139,
+    "setpriority" => 140, "setregid" => 143, "setgid" => 144,
+    "setreuid" => 145, "setuid" => 146, "setresuid" => 147,
+    "setresgid" => 149, "setpgid" => 154, "getpgid" => 155,
+    "getsid" => 156, "setsid" => 157, "prctl" => 167,
+    "getpid" => 172, "getppid" => 173, "getuid" => 174,
+    "geteuid" => 175, "getgid" => 176, "getegid" => 177,
+    "gettid" => 178, "brk" => 214, "munmap" => 215, "mremap" => 216,
+    "clone" => 220, "execve" => 221, "mmap" => 222, "mprotect" => 226,
+    "madvise" => 233, "accept4" => 242, "recvmmsg" => 243,
+    "recvmsg" => 212,
+    "sendmsg" => 211, "listen" => 201, "bind" => 200, "socket" => 198,
+    "connect" => 203, "shutdown" => 210, "sendto" => 206,
+    "recvfrom" => 207, "timerfd_create" => 85,
+    "timerfd_settime" => 86, "timerfd_gettime" => 87,
+    "eventfd2" => 19, "epoll_create1" => 20, "epoll_ctl" => 21,
+    "epoll_pwait" => 22, "getrandom" => 278, "memfd_create" => 279,
+    "execveat" => 281, "statx" => 291, "renameat2" => 276,
+    "prlimit64" => 261, "wait4" => 260, "newfstatat" => 79,
+};
+
+/// Resolves a syscall name to its number on `arch`, using seccompiler's own
+/// table instead of the host's (so a non-native arch can be targeted).
+pub(crate) fn resolve(arch: TargetArch, name: &str) -> Option<i64> {
+    let table = match arch {
+        TargetArch::X86_64 => X86_64,
+        TargetArch::Aarch64 => AARCH64,
+    };
+    table
+        .iter()
+        .find_map(|&(n, nr)| if n == name { Some(nr) } else { None })
+}
+
+/// Every syscall name known for `arch`. Lets the native-backend tests build
+/// a filter with enough rules to exercise far-jump encoding without
+/// hardcoding a second copy of the table.
+#[cfg(test)]
+pub(crate) fn names(arch: TargetArch) -> impl Iterator<Item = &'static str> {
+    let table = match arch {
+        TargetArch::X86_64 => X86_64,
+        TargetArch::Aarch64 => AARCH64,
+    };
+    table.iter().map(|&(n, _)| n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_distinct_recvmsg_and_recvmmsg_on_aarch64() {
+        // __NR_recvmsg and __NR_recvmmsg are easy to swap on arm64 (212 vs
+        // 243); a mixed-up entry silently resolves filters for the wrong
+        // syscall instead of the one actually named in the profile.
+        assert_eq!(resolve(TargetArch::Aarch64, "recvmsg"), Some(212));
+        assert_eq!(resolve(TargetArch::Aarch64, "recvmmsg"), Some(243));
+    }
+
+    #[test]
+    fn unknown_syscall_resolves_to_none() {
+        assert_eq!(resolve(TargetArch::X86_64, "not_a_syscall"), None);
+    }
+}
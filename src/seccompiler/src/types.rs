@@ -0,0 +1,514 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The deserialized shape of the seccompiler JSON input, and the small
+//! enums used to translate it into `libseccomp` and raw BPF terms.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::bindings::*;
+use crate::CompilationError;
+
+/// Target architecture the compiled filter will run on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetArch {
+    X86_64,
+    Aarch64,
+}
+
+impl FromStr for TargetArch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x86_64" => Ok(TargetArch::X86_64),
+            "aarch64" => Ok(TargetArch::Aarch64),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+impl TargetArch {
+    pub(crate) fn to_scmp_type(self) -> u32 {
+        match self {
+            TargetArch::X86_64 => SCMP_ARCH_X86_64,
+            TargetArch::Aarch64 => SCMP_ARCH_AARCH64,
+        }
+    }
+
+    /// The arch this binary was itself built for. Used by `validate_bpf`,
+    /// which actually runs the probe syscalls on this host rather than
+    /// just generating code for a possibly different target.
+    #[cfg(target_arch = "x86_64")]
+    pub fn host() -> Self {
+        TargetArch::X86_64
+    }
+
+    /// The arch this binary was itself built for. Used by `validate_bpf`,
+    /// which actually runs the probe syscalls on this host rather than
+    /// just generating code for a possibly different target.
+    #[cfg(target_arch = "aarch64")]
+    pub fn host() -> Self {
+        TargetArch::Aarch64
+    }
+}
+
+/// Action taken by the kernel when a rule matches (or, for a filter's
+/// `default_action`, when no rule matches).
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeccompAction {
+    Allow,
+    Errno(u32),
+    Kill,
+    Log,
+    Trap,
+}
+
+impl SeccompAction {
+    pub(crate) fn to_scmp_type(self) -> u32 {
+        match self {
+            SeccompAction::Allow => SCMP_ACT_ALLOW,
+            SeccompAction::Errno(errno) => SCMP_ACT_ERRNO_BASE | errno,
+            SeccompAction::Kill => SCMP_ACT_KILL,
+            SeccompAction::Log => SCMP_ACT_LOG,
+            SeccompAction::Trap => SCMP_ACT_TRAP,
+        }
+    }
+}
+
+/// Comparison operator for a single syscall argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeccompCmpOp {
+    Eq,
+    Ge,
+    Gt,
+    Le,
+    Lt,
+    MaskedEq,
+    Ne,
+}
+
+impl SeccompCmpOp {
+    pub(crate) fn to_scmp_type(self) -> u32 {
+        match self {
+            SeccompCmpOp::Ne => SCMP_CMP_NE,
+            SeccompCmpOp::Lt => SCMP_CMP_LT,
+            SeccompCmpOp::Le => SCMP_CMP_LE,
+            SeccompCmpOp::Eq => SCMP_CMP_EQ,
+            SeccompCmpOp::Ge => SCMP_CMP_GE,
+            SeccompCmpOp::Gt => SCMP_CMP_GT,
+            SeccompCmpOp::MaskedEq => SCMP_CMP_MASKED_EQ,
+        }
+    }
+}
+
+/// A single comparison against one of a syscall's arguments.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SeccompCondition {
+    pub arg_index: u8,
+    pub op: SeccompCmpOp,
+    pub val: u64,
+    /// Only meaningful for `MaskedEq`: the mask applied to the argument
+    /// before comparing against `val`. Defaults to `u64::MAX`.
+    #[serde(default = "default_mask")]
+    pub mask: u64,
+}
+
+fn default_mask() -> u64 {
+    u64::MAX
+}
+
+impl SeccompCondition {
+    pub(crate) fn to_scmp_type(&self) -> scmp_arg_cmp {
+        let (op, datum_a) = match self.op {
+            SeccompCmpOp::MaskedEq => (self.op.to_scmp_type(), self.mask),
+            _ => (self.op.to_scmp_type(), self.val),
+        };
+        scmp_arg_cmp {
+            arg: u32::from(self.arg_index),
+            op,
+            datum_a,
+            datum_b: if matches!(self.op, SeccompCmpOp::MaskedEq) {
+                self.val
+            } else {
+                0
+            },
+        }
+    }
+}
+
+fn deserialize_syscall<'de, D>(deserializer: D) -> Result<CString, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+    CString::new(name).map_err(serde::de::Error::custom)
+}
+
+/// A single rule within a thread's filter: "when `syscall` is invoked (and,
+/// if `args` is set, its arguments match), apply the filter's
+/// `filter_action`".
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SeccompRule {
+    #[serde(deserialize_with = "deserialize_syscall")]
+    pub syscall: CString,
+    pub args: Option<Vec<SeccompCondition>>,
+}
+
+/// The full allow-list filter for one Firecracker thread category, plus an
+/// optional second program that narrows it (see [`Denylist`]).
+#[derive(Debug, Clone, Deserialize)]
+pub struct SeccompFilter {
+    pub default_action: SeccompAction,
+    pub filter_action: SeccompAction,
+    pub filter: Vec<SeccompRule>,
+    #[serde(default)]
+    pub denylist: Option<Denylist>,
+}
+
+/// A second BPF program layered on top of a thread's allow-list and
+/// installed alongside it. The kernel applies the most restrictive action
+/// returned by any installed filter that matches a call, so a broad "allow
+/// ioctl" in the main program can be narrowed here by returning
+/// `filter_action` (typically `errno`/`kill`) for specific argument
+/// values, instead of having to express negative conditions in the
+/// allow-list itself. Its implicit default action (what happens when none
+/// of its rules match) is always `allow`, since it only ever narrows.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Denylist {
+    pub filter_action: SeccompAction,
+    pub filter: Vec<SeccompRule>,
+}
+
+/// One entry in a thread's `filter` array: either a syscall rule, or a
+/// reference to a `groups` entry that expands to a set of them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum FilterEntry {
+    Group { group: String },
+    Rule(SeccompRule),
+}
+
+/// A `denylist` block as written in the JSON, before group references have
+/// been expanded.
+#[derive(Debug, Clone, Deserialize)]
+struct RawDenylist {
+    filter_action: SeccompAction,
+    filter: Vec<FilterEntry>,
+}
+
+/// A thread filter as written in the JSON, before group references in
+/// `filter` (and `denylist.filter`) have been expanded.
+#[derive(Debug, Clone, Deserialize)]
+struct RawSeccompFilter {
+    default_action: SeccompAction,
+    filter_action: SeccompAction,
+    filter: Vec<FilterEntry>,
+    #[serde(default)]
+    denylist: Option<RawDenylist>,
+}
+
+/// Expands every [`FilterEntry::Group`] in `entries` against `groups`, then
+/// deduplicates/merges the resulting syscall rules.
+fn expand_entries(
+    entries: Vec<FilterEntry>,
+    groups: &HashMap<String, Vec<SeccompRule>>,
+) -> Result<Vec<SeccompRule>, CompilationError> {
+    let mut rules = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match entry {
+            FilterEntry::Rule(rule) => rules.push(rule),
+            FilterEntry::Group { group } => {
+                let members = groups
+                    .get(&group)
+                    .ok_or_else(|| CompilationError::UndefinedGroup(group.clone()))?;
+                rules.extend(members.iter().cloned());
+            }
+        }
+    }
+    Ok(merge_rules(rules))
+}
+
+fn expand_filter(
+    raw: RawSeccompFilter,
+    groups: &HashMap<String, Vec<SeccompRule>>,
+) -> Result<SeccompFilter, CompilationError> {
+    let filter_action = raw.filter_action;
+    let filter = expand_entries(raw.filter, groups)?;
+
+    let denylist = raw
+        .denylist
+        .map(|raw_denylist| -> Result<Denylist, CompilationError> {
+            // The denylist's own implicit default action is always `allow`
+            // (see `Denylist`'s doc comment), so its `filter_action` has to
+            // be strictly more restrictive than `allow` to narrow anything
+            // -- not merely more restrictive than the main program's own
+            // filter_action, which may itself be something other than
+            // `allow` (e.g. `log`).
+            if raw_denylist.filter_action.to_scmp_type() >= SeccompAction::Allow.to_scmp_type() {
+                return Err(CompilationError::DenylistNotRestrictive(
+                    raw_denylist.filter_action,
+                ));
+            }
+            Ok(Denylist {
+                filter_action: raw_denylist.filter_action,
+                filter: expand_entries(raw_denylist.filter, groups)?,
+            })
+        })
+        .transpose()?;
+
+    Ok(SeccompFilter {
+        default_action: raw.default_action,
+        filter_action,
+        filter,
+        denylist,
+    })
+}
+
+/// Deduplicates a flattened rule list by syscall. Identical argument-rule
+/// variants collapse to one; an unconditional (`args: None`) entry for a
+/// syscall is a strict superset of any argument-conditioned variant also
+/// present, so it wins and the others are dropped.
+fn merge_rules(rules: Vec<SeccompRule>) -> Vec<SeccompRule> {
+    let mut order: Vec<CString> = Vec::new();
+    let mut unconditional: HashSet<CString> = HashSet::new();
+    let mut variants: HashMap<CString, Vec<Vec<SeccompCondition>>> = HashMap::new();
+
+    for rule in rules {
+        if !order.contains(&rule.syscall) {
+            order.push(rule.syscall.clone());
+        }
+        match rule.args {
+            None => {
+                unconditional.insert(rule.syscall);
+            }
+            Some(conditions) => {
+                let entry = variants.entry(rule.syscall).or_default();
+                if !entry.contains(&conditions) {
+                    entry.push(conditions);
+                }
+            }
+        }
+    }
+
+    order
+        .into_iter()
+        .flat_map(|syscall| {
+            if unconditional.contains(&syscall) {
+                vec![SeccompRule {
+                    syscall,
+                    args: None,
+                }]
+            } else {
+                variants
+                    .remove(&syscall)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(move |conditions| SeccompRule {
+                        syscall: syscall.clone(),
+                        args: Some(conditions),
+                    })
+                    .collect()
+            }
+        })
+        .collect()
+}
+
+/// The JSON file's shape before `groups` references have been expanded:
+/// the `groups` map itself, plus every other top-level key (a thread
+/// category name -> its as-written filter).
+#[derive(Debug, Clone, Deserialize)]
+struct RawBpfJson {
+    #[serde(default)]
+    groups: HashMap<String, Vec<SeccompRule>>,
+    #[serde(flatten)]
+    filters: HashMap<String, RawSeccompFilter>,
+}
+
+/// Top-level deserialized form of a seccomp JSON filter file: thread
+/// category name -> its filter. A `groups` key, if present, maps a name to
+/// a reusable list of syscall rules that a thread's `filter` entries can
+/// reference by `{"group": "<name>"}` instead of spelling every syscall
+/// out again.
+#[derive(Debug, Clone)]
+pub struct BpfJson(pub HashMap<String, SeccompFilter>);
+
+impl BpfJson {
+    /// Parses `json`, expanding `groups` references and validating the
+    /// result (undefined group, insufficiently restrictive denylist, ...).
+    /// Shape errors (missing fields, wrong types) surface as
+    /// `CompilationError::JsonDeserialize`; everything past that gets its
+    /// own dedicated variant.
+    pub(crate) fn parse(json: &str) -> Result<Self, CompilationError> {
+        let raw: RawBpfJson =
+            serde_json::from_str(json).map_err(CompilationError::JsonDeserialize)?;
+
+        let mut threads = HashMap::with_capacity(raw.filters.len());
+        for (name, raw_filter) in raw.filters {
+            threads.insert(name, expand_filter(raw_filter, &raw.groups)?);
+        }
+
+        Ok(BpfJson(threads))
+    }
+}
+
+/// Controls which action codes `compile_bpf` actually installs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompilationMode {
+    /// Install `default_action`/`filter_action` as written in the filter.
+    #[default]
+    Enforce,
+    /// Override every `default_action`/`filter_action` to `SCMP_ACT_LOG`,
+    /// keeping the rule structure (syscalls, argument comparators) intact.
+    /// Useful for bringing up a new filter: run under this mode, collect
+    /// the `SECCOMP` audit lines for what would have been denied, then
+    /// fold them back into the JSON.
+    LogOnly,
+}
+
+impl CompilationMode {
+    pub(crate) fn resolve_action(self, action: SeccompAction) -> u32 {
+        match self {
+            CompilationMode::Enforce => action.to_scmp_type(),
+            CompilationMode::LogOnly => SCMP_ACT_LOG,
+        }
+    }
+}
+
+/// Which code path `compile_bpf` uses to turn a [`SeccompFilter`] into a
+/// BPF program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Shell out to `libseccomp` via a `memfd` round-trip, resolving
+    /// syscalls against the host's tables.
+    #[default]
+    LibSeccomp,
+    /// Generate the `sock_filter` program directly in Rust, so the target
+    /// arch doesn't have to match the build host's.
+    Native,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_reference_expands_and_merges_with_inline_rules() {
+        let json = r#"{
+            "groups": {
+                "net": [
+                    {"syscall": "close", "args": null},
+                    {"syscall": "read", "args": [{"arg_index": 0, "op": "eq", "val": 1}]}
+                ]
+            },
+            "vmm": {
+                "default_action": "kill",
+                "filter_action": "allow",
+                "filter": [
+                    {"group": "net"},
+                    {"syscall": "read", "args": [{"arg_index": 0, "op": "eq", "val": 2}]},
+                    {"syscall": "read", "args": [{"arg_index": 0, "op": "eq", "val": 1}]}
+                ]
+            }
+        }"#;
+
+        let parsed = BpfJson::parse(json).unwrap();
+        let vmm = &parsed.0["vmm"];
+
+        // "close" appears once despite being pulled in only via the group.
+        assert_eq!(
+            vmm.filter
+                .iter()
+                .filter(|r| r.syscall.to_str() == Ok("close"))
+                .count(),
+            1
+        );
+        // The duplicate "read" variant (val: 1, once inline and once via the
+        // group) collapses to a single entry, while the distinct "read"
+        // variant (val: 2) survives as a second one -- union, not override.
+        let read_variants: Vec<_> = vmm
+            .filter
+            .iter()
+            .filter(|r| r.syscall.to_str() == Ok("read"))
+            .collect();
+        assert_eq!(read_variants.len(), 2);
+    }
+
+    #[test]
+    fn unconditional_rule_supersedes_its_argument_variants() {
+        let json = r#"{
+            "vmm": {
+                "default_action": "kill",
+                "filter_action": "allow",
+                "filter": [
+                    {"syscall": "ioctl", "args": [{"arg_index": 0, "op": "eq", "val": 1}]},
+                    {"syscall": "ioctl", "args": null}
+                ]
+            }
+        }"#;
+
+        let parsed = BpfJson::parse(json).unwrap();
+        let filter = &parsed.0["vmm"].filter;
+        assert_eq!(filter.len(), 1);
+        assert!(filter[0].args.is_none());
+    }
+
+    #[test]
+    fn undefined_group_reference_is_an_error() {
+        let json = r#"{
+            "vmm": {
+                "default_action": "kill",
+                "filter_action": "allow",
+                "filter": [{"group": "does-not-exist"}]
+            }
+        }"#;
+
+        match BpfJson::parse(json) {
+            Err(CompilationError::UndefinedGroup(name)) => assert_eq!(name, "does-not-exist"),
+            other => panic!("expected UndefinedGroup, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn denylist_restrictiveness_is_checked_against_allow_not_filter_action() {
+        // The main program's own filter_action being `log` (more
+        // restrictive than the conventional `allow`) shouldn't change what
+        // the denylist has to clear: it only ever narrows an implicit
+        // `allow`, so `log` is restrictive enough for it regardless of what
+        // the main program's filter_action happens to be.
+        let json = r#"{
+            "vmm": {
+                "default_action": "kill",
+                "filter_action": "log",
+                "filter": [{"syscall": "ioctl", "args": null}],
+                "denylist": {
+                    "filter_action": "log",
+                    "filter": [{"syscall": "ioctl", "args": [{"arg_index": 0, "op": "eq", "val": 1}]}]
+                }
+            }
+        }"#;
+        assert!(BpfJson::parse(json).is_ok());
+
+        let json_not_restrictive = r#"{
+            "vmm": {
+                "default_action": "kill",
+                "filter_action": "allow",
+                "filter": [{"syscall": "ioctl", "args": null}],
+                "denylist": {
+                    "filter_action": "allow",
+                    "filter": [{"syscall": "ioctl", "args": [{"arg_index": 0, "op": "eq", "val": 1}]}]
+                }
+            }
+        }"#;
+        assert!(matches!(
+            BpfJson::parse(json_not_restrictive),
+            Err(CompilationError::DenylistNotRestrictive(_))
+        ));
+    }
+}